@@ -4,41 +4,53 @@ use std::{
 	convert::TryFrom,
 	io::Write,
 	net::{IpAddr, Ipv4Addr, Ipv6Addr},
-	process::exit,
+	process::{exit, Command},
 	result::Result as StdResult,
 	str::FromStr,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
 	thread::sleep,
 	time::Duration,
 };
 
 use argh::FromArgs;
-use async_std::{
-	channel,
-	prelude::FutureExt,
-	task::{block_on, spawn, spawn_blocking},
-};
+use async_std::task::{block_on, spawn, spawn_blocking};
 use chrono::{SecondsFormat, Utc};
 use color_eyre::eyre::{eyre, Result};
 use env_logger::{Builder as LogBuilder, Target as LogTarget};
-use futures::{stream::TryStreamExt, TryFutureExt};
+use futures::stream::TryStreamExt;
 use kv_log_macro::{debug, error, info, warn};
 use log::{kv, LevelFilter};
 use pnet::{
 	datalink::{
-		channel as datachannel, interfaces, Channel, ChannelType, Config, NetworkInterface,
+		channel as datachannel, interfaces, Channel, ChannelType, Config, DataLinkReceiver,
+		DataLinkSender, NetworkInterface,
 	},
-	ipnetwork::IpNetwork,
+	ipnetwork::{IpNetwork, Ipv4Network},
 	packet::{
 		arp::{
 			ArpHardwareType, ArpHardwareTypes, ArpOperation, ArpOperations, ArpPacket,
 			MutableArpPacket,
 		},
 		ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket},
+		icmpv6::{
+			checksum as icmpv6_checksum,
+			ndp::{
+				MutableNeighborAdvertisementPacket, NdpOption, NdpOptionTypes,
+				NeighborAdvertisementFlags, NeighborAdvertisementPacket, NeighborSolicitPacket,
+			},
+			Icmpv6Code, Icmpv6Packet, Icmpv6Types,
+		},
+		ip::IpNextHeaderProtocols,
+		ipv4::{self, Ipv4Packet, MutableIpv4Packet},
+		ipv6::{Ipv6Packet, MutableIpv6Packet},
+		udp::{self, MutableUdpPacket, UdpPacket},
 		MutablePacket, Packet,
 	},
 	util::MacAddr,
 };
-use pulse::Signal;
 use rand::{rngs::OsRng, Rng};
 use rtnetlink::{packet::{AddressMessage, rtnl::address::nlas::Nla}, AddressHandle};
 use serde::Serialize;
@@ -62,7 +74,7 @@ struct Args {
 	#[argh(option)]
 	interface: Option<String>,
 
-	/// ip (optionally with subnet, defaults to /32) to announce (required)
+	/// ip (optionally with subnet, defaults to /32) to announce (required unless --dhcp)
 	#[argh(option)]
 	ip: Option<IpNetwork>,
 
@@ -106,6 +118,8 @@ struct Args {
 	/// [fail: exit with code=1]
 	/// [quit: exit with code=0]
 	/// [log: don't exit, only log]
+	/// [defend: send a single defensive announce, backing off if conflicts keep recurring; on
+	/// ipv6 this only logs]
 	/// [no: don't watch]
 	#[argh(option, default = "Default::default()")]
 	watch: Watch,
@@ -118,6 +132,20 @@ struct Args {
 	#[argh(switch)]
 	arp_reply: bool,
 
+	/// run an RFC 5227 Address Conflict Detection probe before claiming the ip (ipv4 only)
+	#[argh(switch)]
+	probe: bool,
+
+	/// answer ARP requests for our ip between announcements, like a proxy-ARP responder (ipv4
+	/// only)
+	#[argh(switch)]
+	respond: bool,
+
+	/// obtain the ip to announce from a dhcp lease instead of a static --ip, renewing (and
+	/// re-announcing) as the lease requires (ipv4 only)
+	#[argh(switch)]
+	dhcp: bool,
+
 	/// don't add/remove the ip to/from the interface
 	#[argh(switch)]
 	unmanaged_ip: bool,
@@ -130,6 +158,11 @@ struct Args {
 	#[argh(switch)]
 	remove_pre_existing_ip: bool,
 
+	/// while the ip is managed, drop forwarded packets destined to it so the local kernel can't
+	/// route claimed traffic back onto the same segment
+	#[argh(switch)]
+	forward_guard: bool,
+
 	/// shorthand for `--delay=0 --jitter=0 --count=1 --watch=no`
 	#[argh(switch)]
 	once: bool,
@@ -158,6 +191,7 @@ enum Watch {
 	Fail,
 	Quit,
 	Log,
+	Defend,
 	No,
 }
 
@@ -175,6 +209,7 @@ impl FromStr for Watch {
 			"fail" => Ok(Self::Fail),
 			"quit" => Ok(Self::Quit),
 			"log" => Ok(Self::Log),
+			"defend" => Ok(Self::Defend),
 			"no" => Ok(Self::No),
 			_ => Err(String::from("invalid --watch value")),
 		}
@@ -291,13 +326,687 @@ fn jittered(base: Duration, jitter: Duration) -> Duration {
 	}
 }
 
+// RFC 5227 Address Conflict Detection timings.
+const PROBE_NUM: usize = 3;
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+const PROBE_MIN: Duration = Duration::from_secs(1);
+const PROBE_MAX: Duration = Duration::from_secs(2);
+const ANNOUNCE_NUM: usize = 2;
+const ANNOUNCE_WAIT: Duration = Duration::from_secs(2);
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+const DEFEND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Build an ethernet frame carrying an ARP packet.
+fn build_arp_packet(
+	mac: MacAddr,
+	eth_dest: MacAddr,
+	op: ArpOperations,
+	sender_proto: Ipv4Addr,
+	target_proto: Ipv4Addr,
+	target_hw: MacAddr,
+) -> Result<Vec<u8>> {
+	let mut arp_buf = vec![0_u8; MutableArpPacket::minimum_packet_size()];
+	let mut arp = MutableArpPacket::new(&mut arp_buf[..])
+		.ok_or_else(|| eyre!("failed to create arp packet"))?;
+
+	arp.set_protocol_type(EtherTypes::Ipv4);
+	arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+	arp.set_hw_addr_len(6);
+	arp.set_proto_addr_len(4);
+	arp.set_sender_hw_addr(mac);
+	arp.set_target_hw_addr(target_hw);
+	arp.set_sender_proto_addr(sender_proto);
+	arp.set_target_proto_addr(target_proto);
+	arp.set_operation(op);
+
+	let mut eth_buf = vec![
+		0_u8;
+		MutableEthernetPacket::minimum_packet_size() + MutableArpPacket::minimum_packet_size()
+	];
+	let mut eth = MutableEthernetPacket::new(&mut eth_buf)
+		.ok_or_else(|| eyre!("failed to create eth packet"))?;
+
+	eth.set_source(mac);
+	eth.set_destination(eth_dest);
+	eth.set_ethertype(EtherTypes::Arp);
+	eth.set_payload(arp.packet_mut());
+
+	Ok(eth_buf)
+}
+
+/// Run the RFC 5227 probe/announce handshake over `tx`/`rx` before the caller claims `ip4`.
+/// Returns `Err` if a conflicting host is found, in which case the claim must be aborted.
+fn probe_v4(
+	tx: &mut dyn DataLinkSender,
+	rx: &mut dyn DataLinkReceiver,
+	mac: MacAddr,
+	ip4: Ipv4Addr,
+) -> Result<()> {
+	wait(Duration::from_millis(
+		OsRng::default().gen_range(0..u64::try_from(PROBE_WAIT.as_millis()).unwrap()),
+	));
+
+	for n in 0..PROBE_NUM {
+		let pkt = build_arp_packet(
+			mac,
+			MacAddr::broadcast(),
+			ArpOperations::Request,
+			Ipv4Addr::UNSPECIFIED,
+			ip4,
+			MacAddr::new(0, 0, 0, 0, 0, 0),
+		)?;
+
+		info!("sending acd probe", { n: n, proto_addr: as_display!(ip4) });
+		tx.send_to(&pkt, None)
+			.transpose()?
+			.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+		if let Some(conflict) = watch_for_conflict(rx, ip4, mac, jittered(PROBE_MIN, PROBE_MAX - PROBE_MIN))? {
+			return Err(eyre!("address in use, acd probe saw conflict from {}", conflict));
+		}
+	}
+
+	if let Some(conflict) = watch_for_conflict(rx, ip4, mac, ANNOUNCE_WAIT)? {
+		return Err(eyre!("address in use, acd probe saw conflict from {}", conflict));
+	}
+
+	for n in 0..ANNOUNCE_NUM {
+		let pkt = build_arp_packet(mac, MacAddr::broadcast(), ArpOperations::Request, ip4, ip4, mac)?;
+
+		info!("sending acd announce", { n: n, proto_addr: as_display!(ip4) });
+		tx.send_to(&pkt, None)
+			.transpose()?
+			.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+		if n + 1 < ANNOUNCE_NUM {
+			wait(ANNOUNCE_INTERVAL);
+		}
+	}
+
+	Ok(())
+}
+
+/// Read ARP packets from `rx` for up to `duration`, returning the MAC of a conflicting host if
+/// one is seen: either a reply claiming `ip4`, or a request whose sender protocol address is
+/// `ip4` but whose sender hardware address isn't ours.
+fn watch_for_conflict(
+	rx: &mut dyn DataLinkReceiver,
+	ip4: Ipv4Addr,
+	mac: MacAddr,
+	duration: Duration,
+) -> Result<Option<MacAddr>> {
+	let deadline = std::time::Instant::now() + duration;
+
+	while std::time::Instant::now() < deadline {
+		let pkt = match rx.next() {
+			Ok(pkt) => pkt,
+			Err(e)
+				if e.kind() == std::io::ErrorKind::TimedOut
+					|| e.kind() == std::io::ErrorKind::WouldBlock =>
+			{
+				continue
+			}
+			Err(e) => return Err(e.into()),
+		};
+
+		let eth = match EthernetPacket::new(pkt) {
+			Some(eth) if eth.get_ethertype() == EtherTypes::Arp => eth,
+			_ => continue,
+		};
+
+		let arp = match ArpPacket::new(eth.payload()) {
+			Some(arp) => arp,
+			None => continue,
+		};
+
+		if arp.get_sender_hw_addr() == mac {
+			continue;
+		}
+
+		let conflict = match arp.get_operation() {
+			ArpOperations::Reply => arp.get_sender_proto_addr() == ip4,
+			ArpOperations::Request => arp.get_sender_proto_addr() == ip4,
+			_ => false,
+		};
+
+		if conflict {
+			return Ok(Some(arp.get_sender_hw_addr()));
+		}
+	}
+
+	Ok(None)
+}
+
+// DHCPv4 (RFC 2131) ports, magic cookie and message types we speak.
+const DHCP_CLIENT_PORT: u16 = 68;
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const DHCP_DISCOVER: u8 = 1;
+const DHCP_OFFER: u8 = 2;
+const DHCP_REQUEST: u8 = 3;
+const DHCP_ACK: u8 = 5;
+const DHCP_NAK: u8 = 6;
+const DHCP_RETRIES: usize = 4;
+const DHCP_RETRY_TIMEOUT: Duration = Duration::from_secs(4);
+const DHCP_DEFAULT_LEASE: Duration = Duration::from_secs(3600);
+
+/// A DHCPv4 lease obtained via [`dhcp_negotiate`]: enough to keep announcing the address and to
+/// renew it with its server before it expires.
+struct DhcpLease {
+	ip: Ipv4Addr,
+	prefix: u8,
+	server: Ipv4Addr,
+	lease: Duration,
+	renew: Duration,
+}
+
+/// The DHCP-meaningful fields extracted from a BOOTP reply.
+struct DhcpReply {
+	msg_type: u8,
+	yiaddr: Ipv4Addr,
+	siaddr: Ipv4Addr,
+	subnet_mask: Option<Ipv4Addr>,
+	server_id: Option<Ipv4Addr>,
+	lease_time: Option<u32>,
+	renewal_time: Option<u32>,
+}
+
+/// Build a BOOTP/DHCP message body (everything from `op` to the option list's terminator).
+fn build_dhcp_bootp(
+	mac: MacAddr,
+	xid: u32,
+	msg_type: u8,
+	ciaddr: Ipv4Addr,
+	requested_ip: Option<Ipv4Addr>,
+	server_id: Option<Ipv4Addr>,
+) -> Vec<u8> {
+	let mut bootp = vec![0_u8; 236];
+	bootp[0] = 1; // op: BOOTREQUEST
+	bootp[1] = 1; // htype: ethernet
+	bootp[2] = 6; // hlen
+	bootp[4..8].copy_from_slice(&xid.to_be_bytes());
+	bootp[10] = 0x80; // flags: ask the server to broadcast its reply
+	bootp[12..16].copy_from_slice(&ciaddr.octets());
+	bootp[28..34].copy_from_slice(&mac.octets());
+
+	bootp.extend_from_slice(&DHCP_MAGIC_COOKIE);
+
+	bootp.extend_from_slice(&[53, 1, msg_type]);
+	bootp.extend_from_slice(&[55, 3, 1, 3, 6]); // parameter request list: mask, router, dns
+
+	if let Some(ip) = requested_ip {
+		bootp.push(50);
+		bootp.push(4);
+		bootp.extend_from_slice(&ip.octets());
+	}
+
+	if let Some(server) = server_id {
+		bootp.push(54);
+		bootp.push(4);
+		bootp.extend_from_slice(&server.octets());
+	}
+
+	bootp.push(255); // end
+
+	bootp
+}
+
+/// Parse a BOOTP/DHCP reply body, returning `None` if it's malformed, for a different
+/// transaction, or missing the DHCP magic cookie.
+fn parse_dhcp_bootp(buf: &[u8], xid: u32) -> Option<DhcpReply> {
+	if buf.len() < 240 || buf[0] != 2 {
+		return None;
+	}
+
+	if u32::from_be_bytes(buf[4..8].try_into().ok()?) != xid {
+		return None;
+	}
+
+	if buf[236..240] != DHCP_MAGIC_COOKIE {
+		return None;
+	}
+
+	let yiaddr = Ipv4Addr::new(buf[16], buf[17], buf[18], buf[19]);
+	let siaddr = Ipv4Addr::new(buf[20], buf[21], buf[22], buf[23]);
+
+	let mut msg_type = 0_u8;
+	let mut subnet_mask = None;
+	let mut server_id = None;
+	let mut lease_time = None;
+	let mut renewal_time = None;
+
+	let mut i = 240;
+	while i < buf.len() {
+		let tag = buf[i];
+		if tag == 255 {
+			break;
+		}
+		if tag == 0 {
+			i += 1;
+			continue;
+		}
+		if i + 1 >= buf.len() {
+			break;
+		}
+
+		let len = buf[i + 1] as usize;
+		let data = buf.get(i + 2..i + 2 + len)?;
+
+		match tag {
+			53 if len == 1 => msg_type = data[0],
+			1 if len == 4 => subnet_mask = Some(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+			54 if len == 4 => server_id = Some(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+			51 if len == 4 => lease_time = Some(u32::from_be_bytes(data.try_into().ok()?)),
+			58 if len == 4 => renewal_time = Some(u32::from_be_bytes(data.try_into().ok()?)),
+			_ => {}
+		}
+
+		i += 2 + len;
+	}
+
+	Some(DhcpReply {
+		msg_type,
+		yiaddr,
+		siaddr,
+		subnet_mask,
+		server_id,
+		lease_time,
+		renewal_time,
+	})
+}
+
+/// Wrap a BOOTP/DHCP message body in an ethernet frame carrying a UDP/IPv4 datagram addressed
+/// from `src_ip`:68 to `dst_ip`:67.
+fn build_dhcp_frame(
+	mac: MacAddr,
+	eth_dst: MacAddr,
+	src_ip: Ipv4Addr,
+	dst_ip: Ipv4Addr,
+	bootp: &[u8],
+) -> Result<Vec<u8>> {
+	let udp_len = MutableUdpPacket::minimum_packet_size() + bootp.len();
+	let mut udp_buf = vec![0_u8; udp_len];
+	let mut udp = MutableUdpPacket::new(&mut udp_buf)
+		.ok_or_else(|| eyre!("failed to create udp packet"))?;
+	udp.set_source(DHCP_CLIENT_PORT);
+	udp.set_destination(DHCP_SERVER_PORT);
+	udp.set_length(u16::try_from(udp_len)?);
+	udp.set_payload(bootp);
+	udp.set_checksum(udp::ipv4_checksum(&udp.to_immutable(), &src_ip, &dst_ip));
+
+	let ip_len = MutableIpv4Packet::minimum_packet_size() + udp_len;
+	let mut ip_buf = vec![0_u8; ip_len];
+	let mut ip = MutableIpv4Packet::new(&mut ip_buf)
+		.ok_or_else(|| eyre!("failed to create ipv4 packet"))?;
+	ip.set_version(4);
+	ip.set_header_length(5);
+	ip.set_total_length(u16::try_from(ip_len)?);
+	ip.set_ttl(64);
+	ip.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+	ip.set_source(src_ip);
+	ip.set_destination(dst_ip);
+	ip.set_payload(udp.packet_mut());
+	ip.set_checksum(ipv4::checksum(&ip.to_immutable()));
+
+	let mut eth_buf = vec![0_u8; MutableEthernetPacket::minimum_packet_size() + ip_len];
+	let mut eth = MutableEthernetPacket::new(&mut eth_buf)
+		.ok_or_else(|| eyre!("failed to create eth packet"))?;
+	eth.set_source(mac);
+	eth.set_destination(eth_dst);
+	eth.set_ethertype(EtherTypes::Ipv4);
+	eth.set_payload(&ip_buf);
+
+	Ok(eth_buf)
+}
+
+/// Wait up to `duration` for a DHCP reply addressed to us for transaction `xid`, ignoring
+/// everything else on the wire.
+fn recv_dhcp_reply(
+	rx: &mut dyn DataLinkReceiver,
+	xid: u32,
+	duration: Duration,
+	shutdown: &AtomicBool,
+) -> Result<Option<DhcpReply>> {
+	let deadline = std::time::Instant::now() + duration;
+
+	while std::time::Instant::now() < deadline {
+		if shutdown.load(Ordering::SeqCst) {
+			return Ok(None);
+		}
+
+		let pkt = match rx.next() {
+			Ok(pkt) => pkt,
+			Err(e)
+				if e.kind() == std::io::ErrorKind::TimedOut
+					|| e.kind() == std::io::ErrorKind::WouldBlock =>
+			{
+				continue
+			}
+			Err(e) => return Err(e.into()),
+		};
+
+		let eth = match EthernetPacket::new(pkt) {
+			Some(eth) if eth.get_ethertype() == EtherTypes::Ipv4 => eth,
+			_ => continue,
+		};
+
+		let ip4pkt = match Ipv4Packet::new(eth.payload()) {
+			Some(ip4pkt) if ip4pkt.get_next_level_protocol() == IpNextHeaderProtocols::Udp => {
+				ip4pkt
+			}
+			_ => continue,
+		};
+
+		let udp = match UdpPacket::new(ip4pkt.payload()) {
+			Some(udp) if udp.get_destination() == DHCP_CLIENT_PORT => udp,
+			_ => continue,
+		};
+
+		if let Some(reply) = parse_dhcp_bootp(udp.payload(), xid) {
+			return Ok(Some(reply));
+		}
+	}
+
+	Ok(None)
+}
+
+/// Send one DHCP message, up to [`DHCP_RETRIES`] times in all, and return the first reply
+/// for `xid` that's either `want_type` or a NAK.
+#[allow(clippy::too_many_arguments)]
+fn dhcp_exchange(
+	tx: &mut dyn DataLinkSender,
+	rx: &mut dyn DataLinkReceiver,
+	mac: MacAddr,
+	xid: u32,
+	msg_type: u8,
+	ciaddr: Ipv4Addr,
+	requested_ip: Option<Ipv4Addr>,
+	server_id: Option<Ipv4Addr>,
+	eth_dst: MacAddr,
+	dst_ip: Ipv4Addr,
+	want_type: u8,
+	shutdown: &AtomicBool,
+) -> Result<Option<DhcpReply>> {
+	let bootp = build_dhcp_bootp(mac, xid, msg_type, ciaddr, requested_ip, server_id);
+	let frame = build_dhcp_frame(mac, eth_dst, ciaddr, dst_ip, &bootp)?;
+
+	for attempt in 0..DHCP_RETRIES {
+		if shutdown.load(Ordering::SeqCst) {
+			return Ok(None);
+		}
+
+		debug!("sending dhcp packet", { msg_type: msg_type, attempt: attempt, xid: xid });
+		tx.send_to(&frame, None)
+			.transpose()?
+			.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+		if let Some(reply) = recv_dhcp_reply(rx, xid, DHCP_RETRY_TIMEOUT, shutdown)? {
+			if reply.msg_type == want_type || reply.msg_type == DHCP_NAK {
+				return Ok(Some(reply));
+			}
+		}
+	}
+
+	Ok(None)
+}
+
+/// Turn an ACK (plus the server that sent it) into the [`DhcpLease`] we'll announce and renew.
+fn dhcp_reply_into_lease(reply: DhcpReply, server: Ipv4Addr) -> Result<DhcpLease> {
+	let prefix = reply
+		.subnet_mask
+		.map(|m| u32::from(m).count_ones() as u8)
+		.unwrap_or(32);
+	let lease = reply
+		.lease_time
+		.map_or(DHCP_DEFAULT_LEASE, |s| Duration::from_secs(u64::from(s)));
+	let renew = reply
+		.renewal_time
+		.map(|s| Duration::from_secs(u64::from(s)))
+		.unwrap_or(lease / 2);
+
+	Ok(DhcpLease {
+		ip: reply.yiaddr,
+		prefix,
+		server,
+		lease,
+		renew,
+	})
+}
+
+/// Run a DHCPv4 DISCOVER/OFFER/REQUEST/ACK exchange over `tx`/`rx` and return the lease offered.
+fn dhcp_negotiate(
+	tx: &mut dyn DataLinkSender,
+	rx: &mut dyn DataLinkReceiver,
+	mac: MacAddr,
+	shutdown: &AtomicBool,
+) -> Result<DhcpLease> {
+	let xid = OsRng::default().gen();
+
+	let offer = dhcp_exchange(
+		tx,
+		rx,
+		mac,
+		xid,
+		DHCP_DISCOVER,
+		Ipv4Addr::UNSPECIFIED,
+		None,
+		None,
+		MacAddr::broadcast(),
+		Ipv4Addr::BROADCAST,
+		DHCP_OFFER,
+		shutdown,
+	)?
+	.ok_or_else(|| eyre!("no dhcp offer received"))?;
+
+	if offer.msg_type != DHCP_OFFER {
+		return Err(eyre!("dhcp server naked our discovery"));
+	}
+
+	info!("received dhcp offer", {
+		ip: as_display!(offer.yiaddr),
+		server: as_display!(offer.siaddr),
+	});
+
+	let server = offer.server_id.unwrap_or(offer.siaddr);
+	let ack = dhcp_exchange(
+		tx,
+		rx,
+		mac,
+		xid,
+		DHCP_REQUEST,
+		Ipv4Addr::UNSPECIFIED,
+		Some(offer.yiaddr),
+		Some(server),
+		MacAddr::broadcast(),
+		Ipv4Addr::BROADCAST,
+		DHCP_ACK,
+		shutdown,
+	)?
+	.ok_or_else(|| eyre!("no dhcp ack received"))?;
+
+	if ack.msg_type != DHCP_ACK {
+		return Err(eyre!("dhcp server naked our request"));
+	}
+
+	dhcp_reply_into_lease(ack, server)
+}
+
+/// Resolve `target_ip`'s MAC address via ARP, for exchanges (like a dhcp renewal) that need to
+/// unicast at the link layer instead of broadcasting.
+fn arp_resolve(
+	tx: &mut dyn DataLinkSender,
+	rx: &mut dyn DataLinkReceiver,
+	mac: MacAddr,
+	our_ip: Ipv4Addr,
+	target_ip: Ipv4Addr,
+	shutdown: &AtomicBool,
+) -> Result<MacAddr> {
+	let pkt = build_arp_packet(
+		mac,
+		MacAddr::broadcast(),
+		ArpOperations::Request,
+		our_ip,
+		target_ip,
+		MacAddr::new(0, 0, 0, 0, 0, 0),
+	)?;
+
+	for _ in 0..DHCP_RETRIES {
+		if shutdown.load(Ordering::SeqCst) {
+			return Err(eyre!("shutdown requested while resolving {}", target_ip));
+		}
+
+		tx.send_to(&pkt, None)
+			.transpose()?
+			.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+		let deadline = std::time::Instant::now() + DHCP_RETRY_TIMEOUT;
+		while std::time::Instant::now() < deadline {
+			if shutdown.load(Ordering::SeqCst) {
+				return Err(eyre!("shutdown requested while resolving {}", target_ip));
+			}
+
+			let frame = match rx.next() {
+				Ok(frame) => frame,
+				Err(e)
+					if e.kind() == std::io::ErrorKind::TimedOut
+						|| e.kind() == std::io::ErrorKind::WouldBlock =>
+				{
+					continue
+				}
+				Err(e) => return Err(e.into()),
+			};
+
+			let eth = match EthernetPacket::new(frame) {
+				Some(eth) if eth.get_ethertype() == EtherTypes::Arp => eth,
+				_ => continue,
+			};
+			let arp = match ArpPacket::new(eth.payload()) {
+				Some(arp) => arp,
+				None => continue,
+			};
+
+			if arp.get_operation() == ArpOperations::Reply
+				&& arp.get_sender_proto_addr() == target_ip
+			{
+				return Ok(arp.get_sender_hw_addr());
+			}
+		}
+	}
+
+	Err(eyre!("failed to resolve mac address for {}", target_ip))
+}
+
+/// Renew `lease` directly with its server over a unicast link-layer exchange; falls back to a
+/// fresh [`dhcp_negotiate`] if the server's mac can't be resolved, it doesn't answer, or it
+/// naks the renewal.
+fn dhcp_renew(
+	tx: &mut dyn DataLinkSender,
+	rx: &mut dyn DataLinkReceiver,
+	mac: MacAddr,
+	lease: &DhcpLease,
+	shutdown: &AtomicBool,
+) -> Result<DhcpLease> {
+	let server_mac = match arp_resolve(tx, rx, mac, lease.ip, lease.server, shutdown) {
+		Ok(server_mac) => server_mac,
+		Err(e) => {
+			warn!("failed to resolve dhcp server's mac, restarting discovery: {}", e);
+			return dhcp_negotiate(tx, rx, mac, shutdown);
+		}
+	};
+
+	let xid = OsRng::default().gen();
+
+	let ack = dhcp_exchange(
+		tx,
+		rx,
+		mac,
+		xid,
+		DHCP_REQUEST,
+		lease.ip,
+		None,
+		None,
+		server_mac,
+		lease.server,
+		DHCP_ACK,
+		shutdown,
+	)?;
+
+	match ack {
+		Some(ack) if ack.msg_type == DHCP_ACK => dhcp_reply_into_lease(ack, lease.server),
+		_ => {
+			warn!("dhcp renewal failed, restarting discovery");
+			dhcp_negotiate(tx, rx, mac, shutdown)
+		}
+	}
+}
+
+/// Destination MAC for the all-nodes multicast address, per RFC 2464.
+const ALL_NODES_MAC: MacAddr = MacAddr(0x33, 0x33, 0x00, 0x00, 0x00, 0x01);
+
+/// The all-nodes multicast address, ff02::1.
+const ALL_NODES_IP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// Build an ethernet frame containing an unsolicited Neighbor Advertisement for `target`,
+/// with a Target Link-Layer Address option set to `mac`.
+fn build_neighbor_advertisement(mac: MacAddr, target: Ipv6Addr) -> Result<Vec<u8>> {
+	let options = vec![NdpOption {
+		option_type: NdpOptionTypes::TargetLLAddr,
+		length: 1,
+		data: mac.octets().to_vec(),
+	}];
+
+	// each ndp option's `length` is a count of 8-octet units, including its own type+length bytes
+	let options_len: usize = options.iter().map(|o| usize::from(o.length) * 8).sum();
+	let na_len = MutableNeighborAdvertisementPacket::minimum_packet_size() + options_len;
+	let mut na_buf = vec![0_u8; na_len];
+	let mut na = MutableNeighborAdvertisementPacket::new(&mut na_buf[..])
+		.ok_or_else(|| eyre!("failed to create neighbor advertisement packet"))?;
+
+	na.set_icmpv6_type(Icmpv6Types::NeighborAdvertisement);
+	na.set_icmpv6_code(Icmpv6Code(0));
+	na.set_flags(NeighborAdvertisementFlags::Override);
+	na.set_target_addr(target);
+	na.set_options(&options);
+	na.set_checksum(icmpv6_checksum(
+		&Icmpv6Packet::new(na.packet()).ok_or_else(|| eyre!("failed to reparse na packet"))?,
+		&target,
+		&ALL_NODES_IP,
+	));
+
+	let mut ip6_buf = vec![0_u8; MutableIpv6Packet::minimum_packet_size() + na_len];
+	let mut ip6 = MutableIpv6Packet::new(&mut ip6_buf[..])
+		.ok_or_else(|| eyre!("failed to create ipv6 packet"))?;
+
+	ip6.set_version(6);
+	ip6.set_traffic_class(0);
+	ip6.set_flow_label(0);
+	ip6.set_payload_length(u16::try_from(na_len)?);
+	ip6.set_next_header(IpNextHeaderProtocols::Icmpv6);
+	ip6.set_hop_limit(255);
+	ip6.set_source(target);
+	ip6.set_destination(ALL_NODES_IP);
+	ip6.set_payload(na.packet_mut());
+
+	let mut eth_buf =
+		vec![0_u8; MutableEthernetPacket::minimum_packet_size() + ip6_buf.len()];
+	let mut eth = MutableEthernetPacket::new(&mut eth_buf)
+		.ok_or_else(|| eyre!("failed to create eth packet"))?;
+
+	eth.set_source(mac);
+	eth.set_destination(ALL_NODES_MAC);
+	eth.set_ethertype(EtherTypes::Ipv6);
+	eth.set_payload(&ip6_buf);
+
+	Ok(eth_buf)
+}
+
 fn main() -> Result<()> {
 	// panics+prep errors get color-eyre'd, run errors get logged
 	color_eyre::install()?;
 
 	if let Some(p) = prep()? {
 		debug!("arguments", {
-			ip: &p.0.to_string(),
+			ip: &p.0.map(|ip| ip.to_string()).unwrap_or_else(|| String::from("dhcp")),
 			interface: &p.1.to_string(),
 			mac: &p.2.to_string(),
 			ip_managed: p.3,
@@ -313,7 +1022,7 @@ fn main() -> Result<()> {
 	Ok(())
 }
 
-type Prep = (IpNetwork, NetworkInterface, MacAddr, bool, Args);
+type Prep = (Option<IpNetwork>, NetworkInterface, MacAddr, bool, Args);
 
 fn prep() -> Result<Option<Prep>> {
 	let (ip, args) = {
@@ -349,6 +1058,14 @@ fn prep() -> Result<Option<Prep>> {
 			return Err(eyre!("jitter > interval makes no sense"));
 		}
 
+		if args.forward_guard && args.unmanaged_ip {
+			return Err(eyre!("--forward-guard makes no sense with --unmanaged-ip"));
+		}
+
+		if args.dhcp && args.ip.is_some() {
+			return Err(eyre!("--dhcp makes no sense with a static --ip"));
+		}
+
 		if args.delay > Duration::from_secs(60 * 60 * 24) {
 			warn!("delay > 24h is probably a mistake");
 		}
@@ -357,12 +1074,19 @@ fn prep() -> Result<Option<Prep>> {
 			warn!("interval > 24h is probably a mistake");
 		}
 
-		match (args.ip, &args.interface) {
-			(Some(ip), Some(_)) => (ip, args),
-			(Some(_), None) => return Err(eyre!("missing required option: --interface")),
-			(None, Some(_)) => return Err(eyre!("missing required option: --ip")),
-			(None, None) => return Err(eyre!("missing required options: --interface, --ip")),
-		}
+		let ip = match (args.ip, args.dhcp, &args.interface) {
+			(Some(ip), false, Some(_)) => Some(ip),
+			(Some(_), false, None) => return Err(eyre!("missing required option: --interface")),
+			(None, true, Some(_)) => None,
+			(None, true, None) => return Err(eyre!("missing required option: --interface")),
+			(None, false, Some(_)) => return Err(eyre!("missing required option: --ip (or --dhcp)")),
+			(None, false, None) => {
+				return Err(eyre!("missing required options: --interface, --ip"))
+			}
+			(Some(_), true, _) => unreachable!("rejected above: --dhcp with --ip"),
+		};
+
+		(ip, args)
 	};
 
 	let interface = interfaces()
@@ -393,6 +1117,7 @@ async fn run((ip, interface, mac, mut ip_managed, args): Prep) -> Result<()> {
 		Config {
 			channel_type: ChannelType::Layer2,
 			promiscuous: true,
+			read_timeout: Some(Duration::from_millis(200)),
 			..Default::default()
 		},
 	)? {
@@ -400,17 +1125,52 @@ async fn run((ip, interface, mac, mut ip_managed, args): Prep) -> Result<()> {
 		_ => unimplemented!("internal: unhandled datachannel type"),
 	};
 
-	// TODO: use dialectic session types?
-	let (oconnor, terminator) = channel::bounded(1);
+	let shutdown = Arc::new(AtomicBool::new(false));
+	let ctrlc_shutdown = shutdown.clone();
 	ctrlc::set_handler(move || {
-		oconnor
-			.try_send(())
-			.expect("failed to exit, so exiting harder (unclean)");
+		ctrlc_shutdown.store(true, Ordering::SeqCst);
 	})?;
 
+	let mut dhcp_lease: Option<DhcpLease> = None;
+	let ip = match ip {
+		Some(ip) => ip,
+		None => {
+			info!("starting dhcp negotiation", { interface: interface.index });
+			let lease = dhcp_negotiate(&mut *tx, &mut *rx, mac, &shutdown)?;
+			info!("dhcp lease acquired", {
+				ip: as_display!(lease.ip),
+				server: as_display!(lease.server),
+				lease_secs: lease.lease.as_secs(),
+				renew_secs: lease.renew.as_secs(),
+			});
+			let ip = IpNetwork::V4(Ipv4Network::new(lease.ip, lease.prefix)?);
+			dhcp_lease = Some(lease);
+			ip
+		}
+	};
+
+	if args.probe {
+		match ip {
+			IpNetwork::V4(ip4) => {
+				info!("starting rfc 5227 acd probe", { ip: as_display!(ip4.ip()) });
+				probe_v4(&mut *tx, &mut *rx, mac, ip4.ip())?;
+			}
+			IpNetwork::V6(_) => warn!("--probe is only supported for ipv4, ignoring"),
+		}
+	}
+
 	let (nlconn, nl, _) = rtnetlink::new_connection()?;
 	let nlah = AddressHandle::new(nl);
 
+	// tracks the announced ip across dhcp renewals that change address; everything after the
+	// blaster/listener task below must read through this instead of the initial `ip`
+	let current_ip = Arc::new(Mutex::new(ip));
+
+	// tracks whether we actually installed the forward-guard rule, independently of
+	// `ip_managed` (which may flip false below if a pre-existing ip is left alone) so teardown
+	// only ever removes a rule we actually put in place
+	let mut guard_installed = false;
+
 	if ip_managed {
 		debug!("starting netlink connection");
 		spawn(nlconn);
@@ -431,34 +1191,159 @@ async fn run((ip, interface, mac, mut ip_managed, args): Prep) -> Result<()> {
 				.execute()
 				.await?;
 		}
+
+		if ip_managed && args.forward_guard {
+			info!("installing forwarding guard", { ip: as_display!(ip) });
+			install_forward_guard(ip)?;
+			guard_installed = true;
+		}
 	}
 
-	let (listener, blaster) = match ip {
+	let task = match ip {
 		IpNetwork::V4(_) => {
-			let (watch_signal, mut watch_pulse) = if args.watch_immediately || args.count == 0 {
-				(Signal::pulsed(), None)
-			} else {
-				let (s, p) = Signal::new();
-				(s, Some(p))
-			};
-
 			let watch = args.watch;
 			let watch_delay = args.watch_delay;
+			let respond = args.respond;
+			let defend_target = args.target;
+			let dhcp_nlah = nlah.clone();
+			let dhcp_interface = interface.clone();
+			let dhcp_current_ip = current_ip.clone();
+			let dhcp_guard_installed = guard_installed;
+			let dhcp_ip_managed = ip_managed;
+			let mut lease = dhcp_lease;
+
+			spawn_blocking(move || -> Result<()> {
+				wait(args.delay);
+
+				// the watcher only comes online once the first announce has gone out, unless
+				// asked to start immediately (or --count is 0, so announces never stop)
+				let mut watching = args.watch_immediately || args.count == 0;
+				let mut watching_from =
+					watching.then(|| std::time::Instant::now() + watch_delay);
+				let mut last_defended: Option<std::time::Instant> = None;
 
-			let listener = spawn_blocking(move || -> Result<()> {
-				if let Watch::No = watch {
-					return Ok(());
+				if matches!(watch, Watch::No) {
+					if respond {
+						info!("responding to arp requests for our ip");
+					}
+				} else {
+					info!("watching for competing arp announcements");
 				}
 
-				watch_signal
-					.wait()
-					.map_err(|_| eyre!("failed to wait on watch signal"))?;
-				wait(watch_delay);
+				let mut ip4 = match ip.ip() {
+					IpAddr::V4(i) => i,
+					_ => unreachable!(),
+				};
+				let mut next_renew = lease.as_ref().map(|l| std::time::Instant::now() + l.renew);
 
-				info!("watching for competing arp announcements");
+				let mut n = 0_usize;
+				let mut next_announce = std::time::Instant::now();
 
 				loop {
-					let pkt = rx.next()?;
+					if shutdown.load(Ordering::SeqCst) {
+						return Ok(());
+					}
+
+					if let Some(deadline) = next_renew {
+						if std::time::Instant::now() >= deadline {
+							let current_lease = lease.as_ref().expect("next_renew implies lease");
+							info!("renewing dhcp lease", { ip: as_display!(current_lease.ip) });
+
+							let renewed =
+								dhcp_renew(&mut *tx, &mut *rx, mac, current_lease, &shutdown)?;
+							if renewed.ip != ip4 {
+								warn!("dhcp server handed us a new address on renewal", {
+									old: as_display!(ip4),
+									new: as_display!(renewed.ip),
+								});
+
+								if dhcp_ip_managed {
+									block_on(async {
+										let old =
+											IpNetwork::V4(Ipv4Network::new(ip4, current_lease.prefix)?);
+										if let Some(addr) =
+											find_addr_for_ip(&dhcp_nlah, dhcp_interface.clone(), old).await?
+										{
+											dhcp_nlah.del(addr).execute().await?;
+										}
+										dhcp_nlah
+											.add(dhcp_interface.index, IpAddr::V4(renewed.ip), renewed.prefix)
+											.execute()
+											.await?;
+										Ok::<(), color_eyre::eyre::Error>(())
+									})?;
+								}
+
+								if dhcp_guard_installed {
+									let old = IpNetwork::V4(Ipv4Network::new(ip4, current_lease.prefix)?);
+									let new = IpNetwork::V4(Ipv4Network::new(renewed.ip, renewed.prefix)?);
+
+									if let Err(e) = remove_forward_guard(old) {
+										warn!("{}", e);
+									}
+									info!("installing forwarding guard", { ip: as_display!(new) });
+									install_forward_guard(new)?;
+								}
+
+								ip4 = renewed.ip;
+								*dhcp_current_ip.lock().unwrap() =
+									IpNetwork::V4(Ipv4Network::new(ip4, renewed.prefix)?);
+								next_announce = std::time::Instant::now();
+							}
+
+							next_renew = Some(std::time::Instant::now() + renewed.renew);
+							lease = Some(renewed);
+							continue;
+						}
+					}
+
+					if std::time::Instant::now() >= next_announce {
+						let op = if args.arp_reply {
+							ArpOperations::Reply
+						} else {
+							ArpOperations::Request
+						};
+
+						let eth_buf = build_arp_packet(mac, args.target, op, ip4, ip4, mac)?;
+
+						info!("sending arp packet", {
+							n: n,
+							src: as_display!(mac),
+							dst: as_display!(args.target),
+							op: if args.arp_reply { "reply" } else { "request" },
+							hw: "ethernet",
+							hw_addr: as_display!(mac),
+							proto_addr: as_display!(ip4),
+							gratuitous: true,
+						});
+						tx.send_to(&eth_buf, None)
+							.transpose()?
+							.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+						n = n.saturating_add(1);
+						if args.count > 0 && n >= args.count {
+							return Ok(());
+						}
+
+						if !watching {
+							watching = true;
+							watching_from = Some(std::time::Instant::now() + watch_delay);
+						}
+
+						next_announce = std::time::Instant::now() + jittered(args.interval, args.jitter);
+						continue;
+					}
+
+					let pkt = match rx.next() {
+						Ok(pkt) => pkt,
+						Err(e)
+							if e.kind() == std::io::ErrorKind::TimedOut
+								|| e.kind() == std::io::ErrorKind::WouldBlock =>
+						{
+							continue
+						}
+						Err(e) => return Err(e.into()),
+					};
 					let eth = EthernetPacket::new(pkt)
 						.ok_or_else(|| eyre!("eth packet buffer too small"))?;
 					if eth.get_ethertype() != EtherTypes::Arp {
@@ -495,8 +1380,13 @@ async fn run((ip, interface, mac, mut ip_managed, args): Prep) -> Result<()> {
 						gratuitous: gratuitous,
 					});
 
-					if gratuitous
-						&& arp.get_sender_proto_addr() == ip.ip()
+					let watch_is_due = watching
+						&& watching_from.map(|at| std::time::Instant::now() >= at).unwrap_or(true);
+
+					if !matches!(watch, Watch::No)
+						&& watch_is_due
+						&& gratuitous
+						&& arp.get_sender_proto_addr() == ip4
 						&& arp.get_sender_hw_addr() != mac
 					{
 						match watch {
@@ -521,94 +1411,218 @@ async fn run((ip, interface, mac, mut ip_managed, args): Prep) -> Result<()> {
 									mac: as_display!(arp.get_sender_hw_addr()),
 								});
 							}
+							Watch::Defend => {
+								let recently_defended = last_defended
+									.map(|at| at.elapsed() < DEFEND_INTERVAL)
+									.unwrap_or(false);
+
+								if recently_defended {
+									warn!("received competing announce, backing off defense!", {
+										src: as_display!(eth.get_source()),
+										mac: as_display!(arp.get_sender_hw_addr()),
+									});
+								} else {
+									warn!("received competing announce, defending!", {
+										src: as_display!(eth.get_source()),
+										mac: as_display!(arp.get_sender_hw_addr()),
+									});
+
+									let op = if args.arp_reply {
+										ArpOperations::Reply
+									} else {
+										ArpOperations::Request
+									};
+									let pkt = build_arp_packet(mac, defend_target, op, ip4, ip4, mac)?;
+
+									tx.send_to(&pkt, None)
+										.transpose()?
+										.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+									last_defended = Some(std::time::Instant::now());
+								}
+							}
 						}
 					}
+
+					if respond
+						&& arp.get_operation() == ArpOperations::Request
+						&& arp.get_target_proto_addr() == ip4
+						&& arp.get_sender_hw_addr() != mac
+					{
+						let reply = build_arp_packet(
+							mac,
+							arp.get_sender_hw_addr(),
+							ArpOperations::Reply,
+							ip4,
+							arp.get_sender_proto_addr(),
+							arp.get_sender_hw_addr(),
+						)?;
+
+						info!("responding to arp request", {
+							src: as_display!(eth.get_source()),
+							proto_addr: as_display!(arp.get_sender_proto_addr()),
+						});
+						tx.send_to(&reply, None)
+							.transpose()?
+							.ok_or_else(|| eyre!("unknown error sending packet"))?;
+					}
 				}
-			});
+			})
+		}
+		IpNetwork::V6(ip6) => {
+			let watch = args.watch;
+			let watch_delay = args.watch_delay;
+			let target = ip6.ip();
 
-			let blaster = spawn_blocking(move || -> Result<()> {
+			spawn_blocking(move || -> Result<()> {
 				wait(args.delay);
 
+				let mut watching = args.watch_immediately || args.count == 0;
+				let mut watching_from =
+					watching.then(|| std::time::Instant::now() + watch_delay);
+
+				if !matches!(watch, Watch::No) {
+					info!("watching for competing ndp announcements");
+				}
+
 				let mut n = 0_usize;
+				let mut next_announce = std::time::Instant::now();
+
 				loop {
-					let mut arp_buf = vec![0_u8; MutableArpPacket::minimum_packet_size()];
-					let mut arp = MutableArpPacket::new(&mut arp_buf[..])
-						.ok_or_else(|| eyre!("failed to create arp packet"))?;
+					if shutdown.load(Ordering::SeqCst) {
+						return Ok(());
+					}
 
-					let ip4 = match ip.ip() {
-						IpAddr::V4(i) => i,
-						_ => unreachable!(),
-					};
+					if std::time::Instant::now() >= next_announce {
+						let eth_buf = build_neighbor_advertisement(mac, target)?;
+
+						info!("sending neighbor advertisement", {
+							n: n,
+							src: as_display!(mac),
+							dst: as_display!(ALL_NODES_MAC),
+							target: as_display!(target),
+						});
+						tx.send_to(&eth_buf, None)
+							.transpose()?
+							.ok_or_else(|| eyre!("unknown error sending packet"))?;
+
+						n = n.saturating_add(1);
+						if args.count > 0 && n >= args.count {
+							return Ok(());
+						}
 
-					arp.set_protocol_type(EtherTypes::Ipv4);
-					arp.set_hardware_type(ArpHardwareTypes::Ethernet);
-					arp.set_hw_addr_len(6);
-					arp.set_proto_addr_len(4);
-					arp.set_sender_hw_addr(mac);
-					arp.set_target_hw_addr(mac);
-					arp.set_sender_proto_addr(ip4);
-					arp.set_target_proto_addr(ip4);
-					arp.set_operation(if args.arp_reply {
-						ArpOperations::Reply
-					} else {
-						ArpOperations::Request
-					});
+						if !watching {
+							watching = true;
+							watching_from = Some(std::time::Instant::now() + watch_delay);
+						}
 
-					let mut eth_buf = vec![
-						0_u8;
-						MutableEthernetPacket::minimum_packet_size()
-							+ MutableArpPacket::minimum_packet_size()
-					];
-					let mut eth = MutableEthernetPacket::new(&mut eth_buf)
-						.ok_or_else(|| eyre!("failed to create eth packet"))?;
-
-					eth.set_source(mac);
-					eth.set_destination(args.target);
-					eth.set_ethertype(EtherTypes::Arp);
-					eth.set_payload(arp.packet_mut());
-
-					info!("sending arp packet", {
-						n: n,
-						src: as_display!(mac),
-						dst: as_display!(args.target),
-						op: if args.arp_reply { "reply" } else { "request" },
-						hw: "ethernet",
-						hw_addr: as_display!(mac),
-						proto_addr: as_display!(ip4),
-						gratuitous: true,
-					});
-					tx.send_to(eth.packet(), None)
-						.transpose()?
-						.ok_or_else(|| eyre!("unknown error sending packet"))?;
+						next_announce = std::time::Instant::now() + jittered(args.interval, args.jitter);
+						continue;
+					}
 
-					n = n.saturating_add(1);
-					if args.count > 0 && n >= args.count {
-						return Ok(());
+					if matches!(watch, Watch::No) {
+						wait(Duration::from_millis(200));
+						continue;
+					}
+
+					let pkt = match rx.next() {
+						Ok(pkt) => pkt,
+						Err(e)
+							if e.kind() == std::io::ErrorKind::TimedOut
+								|| e.kind() == std::io::ErrorKind::WouldBlock =>
+						{
+							continue
+						}
+						Err(e) => return Err(e.into()),
+					};
+					let eth = EthernetPacket::new(pkt)
+						.ok_or_else(|| eyre!("eth packet buffer too small"))?;
+					if eth.get_ethertype() != EtherTypes::Ipv6 {
+						continue;
 					}
 
-					if let Some(pulse) = watch_pulse.take() {
-						pulse.pulse();
+					let ip6pkt = Ipv6Packet::new(eth.payload())
+						.ok_or_else(|| eyre!("ipv6 packet buffer too small"))?;
+					if ip6pkt.get_next_header() != IpNextHeaderProtocols::Icmpv6 {
+						continue;
 					}
 
-					wait(jittered(args.interval, args.jitter));
-				}
-			});
+					let icmp6 = Icmpv6Packet::new(ip6pkt.payload())
+						.ok_or_else(|| eyre!("icmpv6 packet buffer too small"))?;
+
+					let (competing, competitor) = match icmp6.get_icmpv6_type() {
+						Icmpv6Types::NeighborAdvertisement => {
+							let na = NeighborAdvertisementPacket::new(ip6pkt.payload())
+								.ok_or_else(|| eyre!("na packet buffer too small"))?;
 
-			(listener, blaster)
+							debug!("read neighbor advertisement", {
+								src: as_display!(eth.get_source()),
+								dst: as_display!(eth.get_destination()),
+								target: as_display!(na.get_target_addr()),
+							});
+
+							(
+								na.get_target_addr() == target && eth.get_source() != mac,
+								eth.get_source(),
+							)
+						}
+						Icmpv6Types::NeighborSolicit => {
+							let ns = NeighborSolicitPacket::new(ip6pkt.payload())
+								.ok_or_else(|| eyre!("ns packet buffer too small"))?;
+
+							let dad = ip6pkt.get_source() == Ipv6Addr::UNSPECIFIED;
+
+							debug!("read neighbor solicitation", {
+								src: as_display!(eth.get_source()),
+								target: as_display!(ns.get_target_addr()),
+								dad: dad,
+							});
+
+							(
+								dad && ns.get_target_addr() == target && eth.get_source() != mac,
+								eth.get_source(),
+							)
+						}
+						_ => continue,
+					};
+
+					let watch_is_due = watching
+						&& watching_from.map(|at| std::time::Instant::now() >= at).unwrap_or(true);
+
+					if competing && watch_is_due {
+						match watch {
+							Watch::No => unreachable!(),
+							Watch::Fail => {
+								return Err(eyre!(
+									"received competing announce! src={}",
+									competitor
+								))
+							}
+							Watch::Quit => {
+								info!("received competing announce!", {
+									src: as_display!(competitor),
+								});
+								return Ok(());
+							}
+							// NDP defense isn't implemented; fall back to logging
+							Watch::Log | Watch::Defend => {
+								warn!("received competing announce!", {
+									src: as_display!(competitor),
+								});
+							}
+						}
+					}
+				}
+			})
 		}
-		IpNetwork::V6(_) => todo!("ipv6 support"),
 	};
 
-	if let Err(err) = terminator
-		.recv()
-		.map_err(|e| e.into())
-		.race(listener)
-		.race(blaster)
-		.await
-	{
+	if let Err(err) = task.await {
 		eprintln!("{:?}", err);
 	}
 
+	let ip = *current_ip.lock().unwrap();
+
 	if ip_managed {
 		info!("removing ip from interface", { ip: as_display!(ip), interface: interface.index });
 		if let Some(addr) = find_addr_for_ip(&nlah, interface, ip).await? {
@@ -616,6 +1630,50 @@ async fn run((ip, interface, mac, mut ip_managed, args): Prep) -> Result<()> {
 		}
 	}
 
+	if guard_installed {
+		info!("removing forwarding guard", { ip: as_display!(ip) });
+		if let Err(e) = remove_forward_guard(ip) {
+			warn!("{}", e);
+		}
+	}
+
+	Ok(())
+}
+
+/// Pick the `iptables`/`ip6tables` binary and the address argument for a forwarding-guard rule.
+fn forward_guard_target(ip: IpNetwork) -> (&'static str, String) {
+	match ip {
+		IpNetwork::V4(ip4) => ("iptables", ip4.ip().to_string()),
+		IpNetwork::V6(ip6) => ("ip6tables", ip6.ip().to_string()),
+	}
+}
+
+/// Install a `FORWARD -d <ip> -j DROP` rule so the kernel can't route traffic for the claimed ip
+/// back onto the segment it came from.
+fn install_forward_guard(ip: IpNetwork) -> Result<()> {
+	let (bin, addr) = forward_guard_target(ip);
+	let status = Command::new(bin)
+		.args(["-I", "FORWARD", "-d", &addr, "-j", "DROP"])
+		.status()?;
+
+	if !status.success() {
+		return Err(eyre!("{} exited with {} installing forward-guard rule", bin, status));
+	}
+
+	Ok(())
+}
+
+/// Remove the rule installed by [`install_forward_guard`].
+fn remove_forward_guard(ip: IpNetwork) -> Result<()> {
+	let (bin, addr) = forward_guard_target(ip);
+	let status = Command::new(bin)
+		.args(["-D", "FORWARD", "-d", &addr, "-j", "DROP"])
+		.status()?;
+
+	if !status.success() {
+		return Err(eyre!("{} exited with {} removing forward-guard rule", bin, status));
+	}
+
 	Ok(())
 }
 